@@ -8,7 +8,31 @@ const WIDTH: usize = 203;
 const HEIGHT: usize = 203;
 
 struct Image {
-    lines: [[[u8; 3]; WIDTH]; HEIGHT],
+    width: usize,
+    height: usize,
+    pixels: Box<[[u8; 3]]>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![[0, 0, 0]; width * height].into_boxed_slice(),
+        }
+    }
+
+    fn get(&self, coord: ImageCoord) -> [u8; 3] {
+        self.pixels[coord.y * self.width + coord.x]
+    }
+
+    fn set(&mut self, coord: ImageCoord, color: [u8; 3]) {
+        self.pixels[coord.y * self.width + coord.x] = color;
+    }
+
+    fn clear(&mut self, color: [u8; 3]) {
+        self.pixels.fill(color);
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -24,13 +48,131 @@ struct ScreenCoord {
 }
 
 fn main() {
-    let mut image = Image {
-        lines: [[[0, 0, 0]; WIDTH]; HEIGHT],
-    };
+    let mut image = Image::new(WIDTH, HEIGHT);
     draw_triangle(ScreenCoord { x: 0.0, y: -0.5 }, ScreenCoord { x: 0.5, y: 0.0 }, ScreenCoord { x: -0.5, y: 0.5 }, &mut image);
     write_ppm(&image, "output");
 }
 
+const CRC32_POLY: u32 = 0xEDB88320;
+
+fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    for n in 0..256 {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                CRC32_POLY ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        table[n] = c;
+    }
+    table
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let table = crc32_table();
+    let mut c: u32 = 0xFFFFFFFF;
+    for &byte in bytes {
+        c = c >> 8 ^ table[((c ^ byte as u32) & 0xFF) as usize];
+    }
+    c ^ 0xFFFFFFFF
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for &byte in bytes {
+        a = (a + byte as u32) % MOD_ADLER;
+        b = (b + a) % MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+fn png_chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(4 + 4 + data.len() + 4);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(chunk_type);
+    chunk.extend_from_slice(data);
+    let crc_input: Vec<u8> = chunk_type.iter().chain(data.iter()).cloned().collect();
+    chunk.extend_from_slice(&crc32(&crc_input).to_be_bytes());
+    chunk
+}
+
+fn deflate_stored(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut offset = 0;
+    if bytes.is_empty() {
+        out.push(0x01);
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&0xFFFFu16.to_le_bytes());
+        return out;
+    }
+    while offset < bytes.len() {
+        let remaining = bytes.len() - offset;
+        let block_len = remaining.min(65535);
+        let is_final = offset + block_len == bytes.len();
+        out.push(if is_final { 0x01 } else { 0x00 });
+        out.extend_from_slice(&(block_len as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block_len as u16)).to_le_bytes());
+        out.extend_from_slice(&bytes[offset..offset + block_len]);
+        offset += block_len;
+    }
+    out
+}
+
+fn zlib_compress(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate_stored(bytes));
+    out.extend_from_slice(&adler32(bytes).to_be_bytes());
+    out
+}
+
+fn write_png(image: &Image, filename: &str) {
+    let path = Path::new(filename);
+    let handle_io_error = |why: &dyn Error| {
+        panic!(
+            "Couldn't write to {}: {}",
+            path.display(),
+            why.description()
+        )
+    };
+    let file = match File::create(&path) {
+        Err(why) => panic!("Couldn't create {}: {}", path.display(), why.description()),
+        Ok(file) => file,
+    };
+    let mut stream = BufWriter::new(file);
+    println!("Writing image to file {}.", filename);
+
+    let mut filtered = Vec::with_capacity(image.height * (1 + image.width * 3));
+    image.pixels.chunks(image.width).for_each(|row| {
+        filtered.push(0u8);
+        row.iter().for_each(|pixel| filtered.extend_from_slice(pixel));
+    });
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(image.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(image.height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = Vec::new();
+    png.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+    png.extend(png_chunk(b"IHDR", &ihdr));
+    png.extend(png_chunk(b"IDAT", &zlib_compress(&filtered)));
+    png.extend(png_chunk(b"IEND", &[]));
+
+    match stream.write(&png) {
+        Err(why) => handle_io_error(&why),
+        Ok(_) => {}
+    }
+    match stream.flush() {
+        Err(why) => handle_io_error(&why),
+        Ok(_) => println!("Successfully wrote to {}.", path.display()),
+    };
+}
+
 fn write_ppm(image: &Image, filename: &str) {
     let path = Path::new(filename);
     let handle_io_error = |why: &dyn Error| {
@@ -46,15 +188,13 @@ fn write_ppm(image: &Image, filename: &str) {
     };
     let mut stream = BufWriter::new(file);
     println!("Writing image to file {}.", filename);
-    match stream.write(format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).as_bytes()) {
+    match stream.write(format!("P6\n{} {}\n255\n", image.width, image.height).as_bytes()) {
         Err(why) => handle_io_error(&why),
         Ok(_) => {}
     }
-    image.lines.iter().for_each(|line| {
-        line.iter().for_each(|pixel| match stream.write(pixel) {
-            Err(why) => handle_io_error(&why),
-            Ok(_) => {}
-        })
+    image.pixels.iter().for_each(|pixel| match stream.write(pixel) {
+        Err(why) => handle_io_error(&why),
+        Ok(_) => {}
     });
     match stream.flush() {
         Err(why) => handle_io_error(&why),
@@ -62,23 +202,23 @@ fn write_ppm(image: &Image, filename: &str) {
     };
 }
 
-fn screen_to_image_coord(screen_coord: ScreenCoord) -> ImageCoord {
+fn screen_to_image_coord(screen_coord: ScreenCoord, width: usize, height: usize) -> ImageCoord {
     ImageCoord {
-        x: ((screen_coord.x + 1.0) * (WIDTH - 1) as f64 / 2.0) as usize,
-        y: ((screen_coord.y + 1.0) * (HEIGHT - 1) as f64 / 2.0) as usize,
+        x: ((screen_coord.x + 1.0) * (width - 1) as f64 / 2.0) as usize,
+        y: ((screen_coord.y + 1.0) * (height - 1) as f64 / 2.0) as usize,
     }
 }
 
-fn image_to_screen_coord(image_coord: ImageCoord) -> ScreenCoord {
+fn image_to_screen_coord(image_coord: ImageCoord, width: usize, height: usize) -> ScreenCoord {
     ScreenCoord {
-        x: image_coord.x as f64 * 2.0 / (WIDTH - 1) as f64 - 1.0,
-        y: image_coord.y as f64 * 2.0 / (HEIGHT - 1) as f64 - 1.0,
+        x: image_coord.x as f64 * 2.0 / (width - 1) as f64 - 1.0,
+        y: image_coord.y as f64 * 2.0 / (height - 1) as f64 - 1.0,
     }
 }
 
 fn draw_point(screen_coord: ScreenCoord, image: &mut Image) {
-    let image_coord = screen_to_image_coord(screen_coord);
-    image.lines[image_coord.y][image_coord.x] = [255, 255, 255];
+    let image_coord = screen_to_image_coord(screen_coord, image.width, image.height);
+    image.set(image_coord, [255, 255, 255]);
 }
 
 fn get_line_eq(a: ScreenCoord, b: ScreenCoord) -> impl Fn(ScreenCoord) -> f64 {
@@ -92,41 +232,295 @@ fn get_line_eq(a: ScreenCoord, b: ScreenCoord) -> impl Fn(ScreenCoord) -> f64 {
 }
 
 fn draw_half_space(a: ScreenCoord, b: ScreenCoord, image: &mut Image) {
-    let pixel_width = 1.0 / ((HEIGHT * HEIGHT + WIDTH * WIDTH) as f64).sqrt();
+    let (width, height) = (image.width, image.height);
+    let pixel_width = 1.0 / ((height * height + width * width) as f64).sqrt();
     let line_eq = get_line_eq(a, b);
-    for y in 0..HEIGHT {
-        for x in 0..WIDTH {
-            let screen_coord = image_to_screen_coord(ImageCoord { x, y });
+    for y in 0..height {
+        for x in 0..width {
+            let screen_coord = image_to_screen_coord(ImageCoord { x, y }, width, height);
             let line_sign = line_eq(screen_coord);
             if line_sign < 0.0 - pixel_width {
-                image.lines[y][x] = [255, 255, 255];
+                image.set(ImageCoord { x, y }, [255, 255, 255]);
             } else if line_sign < 0.0 + pixel_width {
                 let intensity = 128 + (line_sign * 128.0) as u8;
-                image.lines[y][x] = [intensity, intensity, intensity];
+                image.set(ImageCoord { x, y }, [intensity, intensity, intensity]);
             }
         }
     }
 }
 
 fn draw_triangle(a: ScreenCoord, b: ScreenCoord, c: ScreenCoord, image: &mut Image) {
-    let pixel_width = 1.0 / ((HEIGHT * HEIGHT + WIDTH * WIDTH) as f64).sqrt();
+    let (width, height) = (image.width, image.height);
+    let pixel_width = 1.0 / ((height * height + width * width) as f64).sqrt();
     let ab_eq = get_line_eq(a, b);
     let bc_eq = get_line_eq(b, c);
     let ca_eq = get_line_eq(c, a);
-    for y in 0..HEIGHT {
-        for x in 0..WIDTH {
-            let screen_coord = image_to_screen_coord(ImageCoord { x, y });
+    for y in 0..height {
+        for x in 0..width {
+            let screen_coord = image_to_screen_coord(ImageCoord { x, y }, width, height);
             let ab_sign = ab_eq(screen_coord);
             let bc_sign = bc_eq(screen_coord);
             let ca_sign = ca_eq(screen_coord);
             let triangle_sign = ab_sign.min(bc_sign).min(ca_sign);
             if triangle_sign < -pixel_width {
-                image.lines[y][x] = [255, 255, 255];
+                image.set(ImageCoord { x, y }, [255, 255, 255]);
             } else if triangle_sign < 0.0 + pixel_width {
                 let intensity = 128 + (triangle_sign * 128.0) as u8;
-                image.lines[y][x] = [intensity, intensity, intensity];
+                image.set(ImageCoord { x, y }, [intensity, intensity, intensity]);
+            }
+        }
+    }
+}
+
+fn draw_triangle_colored(
+    a: ScreenCoord,
+    b: ScreenCoord,
+    c: ScreenCoord,
+    color_a: [u8; 3],
+    color_b: [u8; 3],
+    color_c: [u8; 3],
+    image: &mut Image,
+) {
+    let (width, height) = (image.width, image.height);
+    let pixel_width = 1.0 / ((height * height + width * width) as f64).sqrt();
+    let ab_eq = get_line_eq(a, b);
+    let bc_eq = get_line_eq(b, c);
+    let ca_eq = get_line_eq(c, a);
+    let d = (b.y - c.y) * (a.x - c.x) + (c.x - b.x) * (a.y - c.y);
+    if d == 0.0 {
+        return;
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let screen_coord = image_to_screen_coord(ImageCoord { x, y }, width, height);
+            let ab_sign = ab_eq(screen_coord);
+            let bc_sign = bc_eq(screen_coord);
+            let ca_sign = ca_eq(screen_coord);
+            let triangle_sign = ab_sign.min(bc_sign).min(ca_sign);
+            if triangle_sign < -pixel_width {
+                continue;
+            }
+            let w_a = ((b.y - c.y) * (screen_coord.x - c.x) + (c.x - b.x) * (screen_coord.y - c.y)) / d;
+            let w_b = ((c.y - a.y) * (screen_coord.x - c.x) + (a.x - c.x) * (screen_coord.y - c.y)) / d;
+            let w_c = 1.0 - w_a - w_b;
+            if w_a < 0.0 || w_b < 0.0 || w_c < 0.0 {
+                continue;
+            }
+            let mut color = [0u8; 3];
+            for i in 0..3 {
+                color[i] = (w_a * color_a[i] as f64 + w_b * color_b[i] as f64 + w_c * color_c[i] as f64)
+                    .round()
+                    .clamp(0.0, 255.0) as u8;
+            }
+            if triangle_sign < 0.0 + pixel_width {
+                let coverage = (128.0 + triangle_sign * 128.0) / 255.0;
+                for channel in color.iter_mut() {
+                    *channel = (*channel as f64 * coverage).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+            image.set(ImageCoord { x, y }, color);
+        }
+    }
+}
+
+fn draw_triangle_shaded(
+    a: ScreenCoord,
+    b: ScreenCoord,
+    c: ScreenCoord,
+    shader: impl Fn(ScreenCoord) -> [u8; 3],
+    image: &mut Image,
+) {
+    let (width, height) = (image.width, image.height);
+    let pixel_width = 1.0 / ((height * height + width * width) as f64).sqrt();
+    let ab_eq = get_line_eq(a, b);
+    let bc_eq = get_line_eq(b, c);
+    let ca_eq = get_line_eq(c, a);
+    let d = (b.y - c.y) * (a.x - c.x) + (c.x - b.x) * (a.y - c.y);
+    if d == 0.0 {
+        return;
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let screen_coord = image_to_screen_coord(ImageCoord { x, y }, width, height);
+            let ab_sign = ab_eq(screen_coord);
+            let bc_sign = bc_eq(screen_coord);
+            let ca_sign = ca_eq(screen_coord);
+            let triangle_sign = ab_sign.min(bc_sign).min(ca_sign);
+            if triangle_sign < -pixel_width {
+                continue;
+            }
+            let w_a = ((b.y - c.y) * (screen_coord.x - c.x) + (c.x - b.x) * (screen_coord.y - c.y)) / d;
+            let w_b = ((c.y - a.y) * (screen_coord.x - c.x) + (a.x - c.x) * (screen_coord.y - c.y)) / d;
+            let w_c = 1.0 - w_a - w_b;
+            if w_a < 0.0 || w_b < 0.0 || w_c < 0.0 {
+                continue;
+            }
+            let mut color = shader(screen_coord);
+            if triangle_sign < 0.0 + pixel_width {
+                let coverage = (128.0 + triangle_sign * 128.0) / 255.0;
+                for channel in color.iter_mut() {
+                    *channel = (*channel as f64 * coverage).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+            image.set(ImageCoord { x, y }, color);
+        }
+    }
+}
+
+fn fill_shaded(shader: impl Fn(ScreenCoord) -> [u8; 3], image: &mut Image) {
+    let (width, height) = (image.width, image.height);
+    for y in 0..height {
+        for x in 0..width {
+            let screen_coord = image_to_screen_coord(ImageCoord { x, y }, width, height);
+            image.set(ImageCoord { x, y }, shader(screen_coord));
+        }
+    }
+}
+
+fn draw_filled_circle(center: ScreenCoord, radius: f64, color: [u8; 3], image: &mut Image) {
+    let (width, height) = (image.width, image.height);
+    let pixel_width = 1.0 / ((height * height + width * width) as f64).sqrt();
+    for y in 0..height {
+        for x in 0..width {
+            let screen_coord = image_to_screen_coord(ImageCoord { x, y }, width, height);
+            let dist = (screen_coord.x - center.x).hypot(screen_coord.y - center.y) - radius;
+            if dist < -pixel_width {
+                image.set(ImageCoord { x, y }, color);
+            } else if dist < 0.0 + pixel_width {
+                let coverage = (128.0 + dist * 128.0) / 255.0;
+                let mut blended = color;
+                for channel in blended.iter_mut() {
+                    *channel = (*channel as f64 * coverage) as u8;
+                }
+                image.set(ImageCoord { x, y }, blended);
+            }
+        }
+    }
+}
+
+fn draw_rect(top_left: ScreenCoord, bottom_right: ScreenCoord, color: [u8; 3], image: &mut Image) {
+    let (width, height) = (image.width, image.height);
+    let pixel_width = 1.0 / ((height * height + width * width) as f64).sqrt();
+    for y in 0..height {
+        for x in 0..width {
+            let screen_coord = image_to_screen_coord(ImageCoord { x, y }, width, height);
+            let dist = (top_left.x - screen_coord.x)
+                .max(screen_coord.x - bottom_right.x)
+                .max(top_left.y - screen_coord.y)
+                .max(screen_coord.y - bottom_right.y);
+            if dist < -pixel_width {
+                image.set(ImageCoord { x, y }, color);
+            } else if dist < 0.0 + pixel_width {
+                let coverage = (128.0 + dist * 128.0) / 255.0;
+                let mut blended = color;
+                for channel in blended.iter_mut() {
+                    *channel = (*channel as f64 * coverage) as u8;
+                }
+                image.set(ImageCoord { x, y }, blended);
+            }
+        }
+    }
+}
+
+fn draw_line(a: ScreenCoord, b: ScreenCoord, thickness: f64, color: [u8; 3], image: &mut Image) {
+    let (width, height) = (image.width, image.height);
+    let pixel_width = 1.0 / ((height * height + width * width) as f64).sqrt();
+    let line_eq = get_line_eq(a, b);
+    let half_thickness = thickness / 2.0;
+    let t = ScreenCoord {
+        x: b.x - a.x,
+        y: b.y - a.y,
+    };
+    let len_sq = t.x * t.x + t.y * t.y;
+    for y in 0..height {
+        for x in 0..width {
+            let screen_coord = image_to_screen_coord(ImageCoord { x, y }, width, height);
+            let along = if len_sq > 0.0 {
+                ((screen_coord.x - a.x) * t.x + (screen_coord.y - a.y) * t.y) / len_sq
+            } else {
+                0.0
+            };
+            if along < 0.0 || along > 1.0 {
+                continue;
+            }
+            let dist = line_eq(screen_coord).abs() - half_thickness;
+            if dist < -pixel_width {
+                image.set(ImageCoord { x, y }, color);
+            } else if dist < 0.0 + pixel_width {
+                let coverage = (128.0 + dist * 128.0) / 255.0;
+                let mut blended = color;
+                for channel in blended.iter_mut() {
+                    *channel = (*channel as f64 * coverage) as u8;
+                }
+                image.set(ImageCoord { x, y }, blended);
+            }
+        }
+    }
+}
+
+fn quantize_channel(value: u8) -> u8 {
+    let level = (value as u16 * 6 / 256).min(5);
+    (level * 51) as u8
+}
+
+fn write_sixel(image: &Image, out: &mut impl Write) {
+    let width = image.width;
+    let height = image.height;
+    let mut sixel = String::new();
+    sixel.push_str("\x1bPq");
+
+    let mut y = 0;
+    while y < height {
+        let rows = (height - y).min(6);
+        let mut registers: Vec<[u8; 3]> = Vec::new();
+        let mut pixel_registers = vec![0usize; rows * width];
+        for row in 0..rows {
+            for x in 0..width {
+                let pixel = image.get(ImageCoord { x, y: y + row });
+                let quantized = [
+                    quantize_channel(pixel[0]),
+                    quantize_channel(pixel[1]),
+                    quantize_channel(pixel[2]),
+                ];
+                let register = match registers.iter().position(|&color| color == quantized) {
+                    Some(index) => index,
+                    None => {
+                        registers.push(quantized);
+                        registers.len() - 1
+                    }
+                };
+                pixel_registers[row * width + x] = register;
             }
         }
+
+        for (index, color) in registers.iter().enumerate() {
+            sixel.push_str(&format!(
+                "#{};2;{};{};{}",
+                index,
+                color[0] as u32 * 100 / 255,
+                color[1] as u32 * 100 / 255,
+                color[2] as u32 * 100 / 255
+            ));
+            for x in 0..width {
+                let mut mask = 0u8;
+                for row in 0..rows {
+                    if pixel_registers[row * width + x] == index {
+                        mask |= 1 << row;
+                    }
+                }
+                sixel.push((0x3F + mask) as char);
+            }
+            sixel.push('$');
+        }
+        sixel.push('-');
+        y += 6;
+    }
+
+    sixel.push_str("\x1b\\");
+
+    match out.write_all(sixel.as_bytes()) {
+        Err(why) => panic!("Couldn't write sixel data: {}", why),
+        Ok(_) => {}
     }
 }
 
@@ -137,18 +531,18 @@ mod tests {
     #[test]
     fn test_screen_to_image_coord() {
         assert_eq!(
-            screen_to_image_coord(ScreenCoord { x: 1.0, y: 1.0 }),
+            screen_to_image_coord(ScreenCoord { x: 1.0, y: 1.0 }, WIDTH, HEIGHT),
             ImageCoord {
                 x: WIDTH - 1,
                 y: HEIGHT - 1
             }
         );
         assert_eq!(
-            screen_to_image_coord(ScreenCoord { x: -1.0, y: -1.0 }),
+            screen_to_image_coord(ScreenCoord { x: -1.0, y: -1.0 }, WIDTH, HEIGHT),
             ImageCoord { x: 0, y: 0 }
         );
         assert_eq!(
-            screen_to_image_coord(ScreenCoord { x: 0.0, y: 0.0 }),
+            screen_to_image_coord(ScreenCoord { x: 0.0, y: 0.0 }, WIDTH, HEIGHT),
             ImageCoord {
                 x: (WIDTH - 1) / 2,
                 y: (HEIGHT - 1) / 2
@@ -159,15 +553,259 @@ mod tests {
     #[test]
     fn test_image_to_screen_coord() {
         assert_eq!(
-            image_to_screen_coord(ImageCoord {
-                x: WIDTH - 1,
-                y: HEIGHT - 1
-            }),
+            image_to_screen_coord(
+                ImageCoord {
+                    x: WIDTH - 1,
+                    y: HEIGHT - 1
+                },
+                WIDTH,
+                HEIGHT
+            ),
             ScreenCoord { x: 1.0, y: 1.0 }
         );
         assert_eq!(
-            image_to_screen_coord(ImageCoord { x: 0, y: 0 }),
+            image_to_screen_coord(ImageCoord { x: 0, y: 0 }, WIDTH, HEIGHT),
             ScreenCoord { x: -1.0, y: -1.0 }
         );
     }
+
+    #[test]
+    fn test_crc32_known_vector() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_adler32_known_vector() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn test_deflate_stored_single_block() {
+        let data = b"hello png";
+        let out = deflate_stored(data);
+        assert_eq!(out[0], 0x01);
+        let len = u16::from_le_bytes([out[1], out[2]]);
+        let not_len = u16::from_le_bytes([out[3], out[4]]);
+        assert_eq!(len as usize, data.len());
+        assert_eq!(not_len, !len);
+        assert_eq!(&out[5..], data);
+    }
+
+    #[test]
+    fn test_deflate_stored_multi_block() {
+        let data = vec![0xAB; 70000];
+        let out = deflate_stored(&data);
+
+        let first_final = out[0];
+        let first_len = u16::from_le_bytes([out[1], out[2]]);
+        assert_eq!(first_final, 0x00);
+        assert_eq!(first_len as usize, 65535);
+        let second_offset = 5 + first_len as usize;
+        let second_final = out[second_offset];
+        let second_len = u16::from_le_bytes([out[second_offset + 1], out[second_offset + 2]]);
+        assert_eq!(second_final, 0x01);
+        assert_eq!(second_len as usize, data.len() - 65535);
+        assert_eq!(out.len(), second_offset + 5 + second_len as usize);
+    }
+
+    fn read_png_chunks(bytes: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut chunks = Vec::new();
+        let mut offset = 8;
+        while offset < bytes.len() {
+            let len =
+                u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+                    as usize;
+            let mut chunk_type = [0u8; 4];
+            chunk_type.copy_from_slice(&bytes[offset + 4..offset + 8]);
+            let data = bytes[offset + 8..offset + 8 + len].to_vec();
+            let crc_offset = offset + 8 + len;
+            let crc = u32::from_be_bytes([
+                bytes[crc_offset],
+                bytes[crc_offset + 1],
+                bytes[crc_offset + 2],
+                bytes[crc_offset + 3],
+            ]);
+            let crc_input: Vec<u8> = chunk_type.iter().chain(data.iter()).cloned().collect();
+            assert_eq!(crc, crc32(&crc_input), "bad CRC for chunk {:?}", chunk_type);
+            chunks.push((chunk_type, data));
+            offset = crc_offset + 4;
+        }
+        chunks
+    }
+
+    fn inflate_stored(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut offset = 0;
+        loop {
+            let is_final = bytes[offset] & 1 != 0;
+            let len = u16::from_le_bytes([bytes[offset + 1], bytes[offset + 2]]) as usize;
+            offset += 5;
+            out.extend_from_slice(&bytes[offset..offset + len]);
+            offset += len;
+            if is_final {
+                break;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_write_png_round_trip() {
+        let mut image = Image::new(2, 2);
+        image.set(ImageCoord { x: 0, y: 0 }, [255, 0, 0]);
+        image.set(ImageCoord { x: 1, y: 0 }, [0, 255, 0]);
+        image.set(ImageCoord { x: 0, y: 1 }, [0, 0, 255]);
+        image.set(ImageCoord { x: 1, y: 1 }, [255, 255, 255]);
+
+        let filename = "test_write_png_round_trip.png";
+        write_png(&image, filename);
+        let bytes = std::fs::read(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+
+        assert_eq!(&bytes[0..8], b"\x89PNG\r\n\x1a\n");
+
+        let chunks = read_png_chunks(&bytes);
+        let (ihdr_type, ihdr_data) = &chunks[0];
+        assert_eq!(ihdr_type, b"IHDR");
+        assert_eq!(&ihdr_data[0..4], &2u32.to_be_bytes());
+        assert_eq!(&ihdr_data[4..8], &2u32.to_be_bytes());
+        assert_eq!(ihdr_data[8], 8);
+        assert_eq!(ihdr_data[9], 2);
+
+        let idat: Vec<u8> = chunks
+            .iter()
+            .filter(|(t, _)| t == b"IDAT")
+            .flat_map(|(_, d)| d.clone())
+            .collect();
+        assert_eq!(&idat[0..2], &[0x78, 0x01]);
+        let deflate_data = &idat[2..idat.len() - 4];
+        let adler = u32::from_be_bytes(idat[idat.len() - 4..].try_into().unwrap());
+        let filtered = inflate_stored(deflate_data);
+        assert_eq!(adler, adler32(&filtered));
+
+        let expected_filtered = vec![
+            0, 255, 0, 0, 0, 255, 0, //
+            0, 0, 0, 255, 255, 255, 255,
+        ];
+        assert_eq!(filtered, expected_filtered);
+
+        assert_eq!(chunks.last().unwrap().0, *b"IEND");
+    }
+
+    #[test]
+    fn test_draw_triangle_colored_interpolates_and_rounds() {
+        let width = 41;
+        let height = 41;
+        let mut image = Image::new(width, height);
+        let a = ScreenCoord { x: -0.8, y: -0.8 };
+        let b = ScreenCoord { x: 0.8, y: -0.8 };
+        let c = ScreenCoord { x: 0.0, y: 0.8 };
+        let color_a = [255, 0, 0];
+        let color_b = [0, 255, 0];
+        let color_c = [0, 0, 255];
+        draw_triangle_colored(a, b, c, color_a, color_b, color_c, &mut image);
+
+        let coord = ImageCoord {
+            x: width / 2,
+            y: height / 2,
+        };
+        let p = image_to_screen_coord(coord, width, height);
+        let d = (b.y - c.y) * (a.x - c.x) + (c.x - b.x) * (a.y - c.y);
+        let w_a = ((b.y - c.y) * (p.x - c.x) + (c.x - b.x) * (p.y - c.y)) / d;
+        let w_b = ((c.y - a.y) * (p.x - c.x) + (a.x - c.x) * (p.y - c.y)) / d;
+        let w_c = 1.0 - w_a - w_b;
+        assert!(
+            w_a >= 0.0 && w_b >= 0.0 && w_c >= 0.0,
+            "test point must land inside the triangle interior"
+        );
+
+        let expected = [
+            (w_a * color_a[0] as f64 + w_b * color_b[0] as f64 + w_c * color_c[0] as f64)
+                .round() as u8,
+            (w_a * color_a[1] as f64 + w_b * color_b[1] as f64 + w_c * color_c[1] as f64)
+                .round() as u8,
+            (w_a * color_a[2] as f64 + w_b * color_b[2] as f64 + w_c * color_c[2] as f64)
+                .round() as u8,
+        ];
+        assert_eq!(image.get(coord), expected);
+    }
+
+    #[test]
+    fn test_fill_shaded_varies_per_pixel() {
+        let mut image = Image::new(4, 4);
+        fill_shaded(|p| [((p.x + 1.0) * 127.0) as u8, 0, 0], &mut image);
+        let left = image.get(ImageCoord { x: 0, y: 0 });
+        let right = image.get(ImageCoord { x: 3, y: 0 });
+        assert_ne!(left, right);
+    }
+
+    #[test]
+    fn test_draw_triangle_shaded_colors_the_interior_not_the_corners() {
+        let mut image = Image::new(21, 21);
+        let a = ScreenCoord { x: -0.8, y: -0.8 };
+        let b = ScreenCoord { x: 0.8, y: -0.8 };
+        let c = ScreenCoord { x: 0.0, y: 0.8 };
+        draw_triangle_shaded(a, b, c, |_| [42, 42, 42], &mut image);
+        assert_eq!(image.get(ImageCoord { x: 10, y: 10 }), [42, 42, 42]);
+        assert_eq!(image.get(ImageCoord { x: 0, y: 0 }), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_draw_filled_circle_center_and_outside() {
+        let mut image = Image::new(21, 21);
+        draw_filled_circle(ScreenCoord { x: 0.0, y: 0.0 }, 0.5, [10, 20, 30], &mut image);
+        assert_eq!(image.get(ImageCoord { x: 10, y: 10 }), [10, 20, 30]);
+        assert_eq!(image.get(ImageCoord { x: 0, y: 0 }), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_draw_rect_inside_and_outside() {
+        let mut image = Image::new(21, 21);
+        draw_rect(
+            ScreenCoord { x: -0.5, y: -0.5 },
+            ScreenCoord { x: 0.5, y: 0.5 },
+            [9, 9, 9],
+            &mut image,
+        );
+        assert_eq!(image.get(ImageCoord { x: 10, y: 10 }), [9, 9, 9]);
+        assert_eq!(image.get(ImageCoord { x: 0, y: 0 }), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_draw_line_thickness_and_endpoint_clamping() {
+        let mut image = Image::new(21, 21);
+        draw_line(
+            ScreenCoord { x: -0.5, y: 0.0 },
+            ScreenCoord { x: 0.5, y: 0.0 },
+            0.2,
+            [7, 7, 7],
+            &mut image,
+        );
+        // On the segment, near the line.
+        assert_eq!(image.get(ImageCoord { x: 10, y: 10 }), [7, 7, 7]);
+        // Same row as the line, but beyond the segment's clamped endpoint.
+        assert_eq!(image.get(ImageCoord { x: 0, y: 10 }), [0, 0, 0]);
+        // On the segment's x-span, but too far from the line perpendicular.
+        assert_eq!(image.get(ImageCoord { x: 10, y: 0 }), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_write_sixel_smoke() {
+        let mut image = Image::new(2, 2);
+        image.set(ImageCoord { x: 0, y: 0 }, [255, 0, 0]);
+        image.set(ImageCoord { x: 1, y: 0 }, [0, 255, 0]);
+        image.set(ImageCoord { x: 0, y: 1 }, [0, 0, 255]);
+        image.set(ImageCoord { x: 1, y: 1 }, [0, 0, 0]);
+
+        let mut buffer = Vec::new();
+        write_sixel(&image, &mut buffer);
+
+        assert!(buffer.starts_with(b"\x1bPq"));
+        assert!(buffer.ends_with(b"\x1b\\"));
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.contains("#0;2;100;0;0"));
+        assert!(text.contains('-'));
+    }
 }